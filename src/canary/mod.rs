@@ -0,0 +1,465 @@
+use std::time::Instant;
+
+use probe_rs::{Architecture, Core, CoreRegisterAddress, MemoryInterface, Session};
+use rand::random;
+use thiserror::Error;
+
+use crate::{registers::PC, Elf, TargetInfo, TIMEOUT};
+
+mod riscv;
+mod thumb;
+mod unwind;
+
+use riscv::Riscv32StackPainter;
+use thumb::ThumbStackPainter;
+
+/// Emits the architecture-specific machine code that paints and measures the stack canary.
+///
+/// Each implementation hand-encodes a tiny subroutine for its target architecture; see
+/// [`thumb`] and [`riscv`] for the Cortex-M and RISC-V backends respectively.
+pub(crate) trait StackPainter {
+    /// The length, in bytes, of the subroutine returned by [`paint`](StackPainter::paint).
+    fn paint_subroutine_len(&self) -> usize;
+
+    /// The length, in bytes, of the subroutine returned by [`measure`](StackPainter::measure).
+    fn measure_subroutine_len(&self) -> usize;
+
+    /// The register [`measure`](StackPainter::measure)'s subroutine reports its result in.
+    fn result_register(&self) -> CoreRegisterAddress;
+
+    /// Build a subroutine that paints every word from `start` till `start + size` with
+    /// `address ^ seed`.
+    fn paint(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError>;
+
+    /// Build a subroutine that scans from `start` till `start + size` for the first word that
+    /// doesn't match its expected `address ^ seed` value, reporting it (or `0`) in
+    /// [`result_register`](StackPainter::result_register).
+    fn measure(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError>;
+}
+
+/// Pick the [`StackPainter`] matching the core's architecture.
+fn stack_painter(core: &Core) -> Box<dyn StackPainter> {
+    match core.architecture() {
+        Architecture::Riscv => Box::new(Riscv32StackPainter),
+        _ => Box::new(ThumbStackPainter),
+    }
+}
+
+/// Errors that can occur while placing or reading back the stack canary.
+#[derive(Debug, Error)]
+pub(crate) enum CanaryError {
+    /// An address or length that was required to be 4-byte aligned wasn't.
+    #[error("address/length {address:#010X} is not 4-byte aligned")]
+    MemoryAlignment { address: u32 },
+
+    /// The canary region is too small to hold the paint/measure subroutine.
+    #[error(
+        "the canary subroutine needs {needed} bytes, but only {available} bytes of stack are available"
+    )]
+    SubroutineTooLarge { needed: usize, available: u32 },
+
+    /// The target has no stack range we can place a canary in.
+    #[error("couldn't find a valid stack range; not placing a stack canary")]
+    NoStackRange,
+
+    /// The target's program uses a heap, so its stack usage can't be bounded reliably.
+    #[error("the program uses a heap; not placing a stack canary")]
+    HeapInUse,
+
+    /// Catch-all for probe I/O failures.
+    #[error(transparent)]
+    Other(#[from] probe_rs::Error),
+}
+
+/// (Location of) the stack canary
+///
+/// The stack canary is used to detect *potential* stack overflows
+///
+/// The canary is placed in memory as shown in the diagram below:
+///
+/// ``` text
+/// +--------+ -> initial_stack_pointer / stack_range.end()
+/// |        |
+/// | stack  | (grows downwards)
+/// |        |
+/// +--------+
+/// |        |
+/// |        |
+/// +--------+
+/// | canary |
+/// +--------+ -> stack_range.start()
+/// |        |
+/// | static | (variables, fixed size)
+/// |        |
+/// +--------+ -> lowest RAM address
+/// ```
+///
+/// Before the target program is started, the whole canary is painted with a pattern derived from
+/// a random per-install `seed`: the word at address `a` is set to `a ^ seed` rather than a fixed
+/// constant, so that legitimate stack data can't be mistaken for untouched canary (see `seed`
+/// below). The canary size is 10% of the available stack space or 1 KiB, whichever is smallest.
+///
+/// When the programs ends (due to panic or breakpoint) the integrity of the canary is checked. If it was
+/// "touched" (any of its words no longer matches its expected `address ^ seed` value) then that is
+/// considered to be a *potential* stack overflow.
+#[derive(Clone, Copy)]
+pub(crate) struct Canary {
+    address: u32,
+    size: usize,
+    stack_available: u32,
+    data_below_stack: bool,
+    measure_stack: bool,
+    /// Random value XORed into each painted word, so that the pattern a painted word holds
+    /// depends on its address. Chosen once per [`Canary::install`] to make it vanishingly
+    /// unlikely that legitimate stack contents coincidentally look like untouched canary.
+    seed: u32,
+}
+
+impl Canary {
+    /// Decide if and where to place the stack canary.
+    ///
+    /// Returns `Ok(None)` (logging [`CanaryError::NoStackRange`] or [`CanaryError::HeapInUse`] at
+    /// debug level) if the target has no usable stack range or uses a heap, since those just mean
+    /// "don't place a canary here", not a probe failure worth failing the run over.
+    pub(crate) fn install(
+        sess: &mut Session,
+        target_info: &TargetInfo,
+        elf: &Elf,
+        measure_stack: bool,
+    ) -> Result<Option<Self>, CanaryError> {
+        let mut core = sess.core(0)?;
+        core.reset_and_halt(TIMEOUT)?;
+
+        let stack_info = match target_info.stack_info.as_ref() {
+            Some(stack_info) => stack_info,
+            None => {
+                log::debug!("{}", CanaryError::NoStackRange);
+                return Ok(None);
+            }
+        };
+
+        if elf.program_uses_heap() {
+            log::debug!("{}", CanaryError::HeapInUse);
+            return Ok(None);
+        }
+
+        let stack_start = *stack_info.range.start();
+        let stack_available = *stack_info.range.end() - stack_start;
+
+        let size = if measure_stack {
+            // When measuring stack consumption, we have to color the whole stack.
+            stack_available as usize
+        } else {
+            // We consider >90% stack usage a potential stack overflow, but don't go beyond 1 kb
+            // since filling a lot of RAM is slow (and 1 kb should be "good enough" for what we're
+            // doing).
+            round_up(1024.min(stack_available / 10), 4) as usize
+        };
+
+        log::debug!(
+            "{} bytes of stack available ({:#010X} ..= {:#010X}), using {} byte canary",
+            stack_available,
+            stack_info.range.start(),
+            stack_info.range.end(),
+            size,
+        );
+
+        let size_kb = size as f64 / 1024.0;
+        if measure_stack {
+            // Painting 100KB or more takes a few seconds, so provide user feedback.
+            log::info!(
+                "painting {:.2} KiB of RAM for stack usage estimation",
+                size_kb
+            );
+        }
+        let seed: u32 = random();
+
+        let painter = stack_painter(&core);
+        let start = Instant::now();
+        paint_stack(&mut core, painter.as_ref(), stack_start, size as u32, seed)?;
+        let seconds = start.elapsed().as_secs_f64();
+        log::trace!(
+            "setting up canary took {:.3}s ({:.2} KiB/s)",
+            seconds,
+            size_kb / seconds
+        );
+
+        Ok(Some(Canary {
+            address: stack_start,
+            size,
+            stack_available,
+            data_below_stack: stack_info.data_below_stack,
+            measure_stack,
+            seed,
+        }))
+    }
+
+    pub(crate) fn touched(
+        self,
+        mut core: &mut probe_rs::Core,
+        elf: &Elf,
+    ) -> Result<bool, CanaryError> {
+        let size_kb = self.size as f64 / 1024.0;
+        if self.measure_stack {
+            log::info!(
+                "reading {:.2} KiB of RAM for stack usage estimation",
+                size_kb,
+            );
+        }
+
+        let painter = stack_painter(&*core);
+        let start = Instant::now();
+        let touched_address = measure_stack(
+            &mut core,
+            painter.as_ref(),
+            self.address,
+            self.size as u32,
+            self.seed,
+        )?;
+        let seconds = start.elapsed().as_secs_f64();
+        log::trace!(
+            "reading canary took {:.3}s ({:.2} KiB/s)",
+            seconds,
+            size_kb / seconds
+        );
+
+        let min_stack_usage = match touched_address {
+            Some(touched_address) => {
+                log::debug!("canary was touched at {:#010X}", touched_address);
+                Some(elf.vector_table.initial_stack_pointer - touched_address)
+            }
+            None => None,
+        };
+
+        if self.measure_stack {
+            let min_stack_usage = min_stack_usage.unwrap_or(0);
+            let used_kb = min_stack_usage as f64 / 1024.0;
+            let avail_kb = self.stack_available as f64 / 1024.0;
+            let pct = used_kb / avail_kb * 100.0;
+            log::info!(
+                "program has used at least {:.2}/{:.2} KiB ({:.1}%) of stack space",
+                used_kb,
+                avail_kb,
+                pct,
+            );
+
+            // Don't test for stack overflows if we're measuring stack usage.
+            Ok(false)
+        } else {
+            match min_stack_usage {
+                Some(min_stack_usage) => {
+                    let used_kb = min_stack_usage as f64 / 1024.0;
+                    let avail_kb = self.stack_available as f64 / 1024.0;
+                    let pct = used_kb / avail_kb * 100.0;
+                    log::warn!(
+                        "program has used at least {:.2}/{:.2} KiB ({:.1}%) of stack space",
+                        used_kb,
+                        avail_kb,
+                        pct,
+                    );
+
+                    if let Some(touched_address) = touched_address {
+                        log_frame_breakdown(elf, core, touched_address);
+                    }
+
+                    if self.data_below_stack {
+                        log::warn!("data segments might be corrupted due to stack overflow");
+                    }
+
+                    Ok(true)
+                }
+                None => {
+                    log::debug!("stack canary intact");
+                    Ok(false)
+                }
+            }
+        }
+    }
+}
+
+/// Log the symbol name, source location and per-frame size breakdown of the call stack that
+/// reached `touched_address`, if DWARF call-frame info is available.
+fn log_frame_breakdown(elf: &Elf, core: &mut Core, touched_address: u32) {
+    let frames = match unwind::frames_above(elf, core, touched_address) {
+        Ok(Some(frames)) if !frames.is_empty() => frames,
+        Ok(_) => return,
+        Err(error) => {
+            log::debug!("couldn't unwind the call stack to the stack overflow: {}", error);
+            return;
+        }
+    };
+
+    let last = frames.last().expect("checked non-empty above");
+    if last.is_culprit {
+        log::warn!(
+            "the deepest frame, {} ({}), used {} bytes of stack",
+            last.function.as_deref().unwrap_or("<unknown>"),
+            last.location.as_deref().unwrap_or("<unknown location>"),
+            last.size,
+        );
+    } else {
+        log::warn!("couldn't unwind all the way to the frame that overflowed; showing what we found");
+    }
+
+    for (i, frame) in frames.iter().enumerate() {
+        log::warn!(
+            "{:>4}: {} ({}), {} bytes",
+            i,
+            frame.function.as_deref().unwrap_or("<unknown>"),
+            frame.location.as_deref().unwrap_or("<unknown location>"),
+            frame.size,
+        );
+    }
+}
+
+fn round_up(n: u32, k: u32) -> u32 {
+    let rem = n % k;
+    if rem == 0 {
+        n
+    } else {
+        n + 4 - rem
+    }
+}
+
+/// Check that `value` (an address or a length) is 4-byte aligned.
+fn check_aligned(value: u32) -> Result<(), CanaryError> {
+    if value % 4 == 0 {
+        Ok(())
+    } else {
+        Err(CanaryError::MemoryAlignment { address: value })
+    }
+}
+
+/// Paint the stack with the address-keyed pattern (see [`Canary::seed`](Canary)).
+///
+/// Both `start` and `size` need to be 4-byte-aligned.
+///
+/// The [`Core`] is expected to be halted and will also be halted when this function returns.
+fn paint_stack(
+    core: &mut Core,
+    painter: &dyn StackPainter,
+    start: u32,
+    size: u32,
+    seed: u32,
+) -> Result<(), CanaryError> {
+    let subroutine_len = painter.paint_subroutine_len();
+
+    // does the subroutine fit inside the stack?
+    if subroutine_len > size as usize {
+        return Err(CanaryError::SubroutineTooLarge {
+            needed: subroutine_len,
+            available: size,
+        });
+    }
+
+    // write subroutine to RAM
+    // NOTE: place the subroutine at `start` and have it paint everything above that, so it
+    // never writes past `start + size` (the top of the canary region)
+    let subroutine = painter.paint(
+        start + subroutine_len as u32,
+        size - subroutine_len as u32,
+        seed,
+    )?;
+    core.write_8(start, &subroutine)?;
+
+    // store current PC and set PC to beginning of subroutine
+    let previous_pc = core.read_core_reg(PC)?;
+    core.write_core_reg(PC, start)?;
+
+    // execute the subroutine and wait for it to finish
+    core.run()?;
+    core.wait_for_core_halted(TIMEOUT)?;
+
+    // overwrite subroutine with the pattern it would itself have painted there
+    let mut pattern = vec![0; subroutine_len];
+    for (i, word) in pattern.chunks_exact_mut(4).enumerate() {
+        let addr = start + (i as u32) * 4;
+        word.copy_from_slice(&(addr ^ seed).to_le_bytes());
+    }
+    core.write_8(start, &pattern)?;
+
+    // reset PC to where it was before
+    core.write_core_reg(PC, previous_pc)?;
+
+    Ok(())
+}
+
+/// Create a subroutine to measure if the stack grew into the painted area.
+///
+/// Returns the lowest address whose word no longer matches its expected `address ^ seed` value.
+///
+/// Both `start` and `size` need to be 4-byte-aligned.
+///
+/// The [`Core`] is expected to be halted and will also be halted when this function returns.
+fn measure_stack(
+    core: &mut Core,
+    painter: &dyn StackPainter,
+    start: u32,
+    size: u32,
+    seed: u32,
+) -> Result<Option<u32>, CanaryError> {
+    let subroutine_len = painter.measure_subroutine_len();
+
+    // does the subroutine fit inside the stack?
+    if subroutine_len > size as usize {
+        return Err(CanaryError::SubroutineTooLarge {
+            needed: subroutine_len,
+            available: size,
+        });
+    }
+
+    // NOTE: place the subroutine at `start`, the *bottom* of the painted region, the end
+    // furthest from `initial_stack_pointer`, so that phase 1 below peeks the region least likely
+    // to already be touched. Placing it at the top would mean phase 1 peeks memory right below
+    // `initial_stack_pointer`, which the program's very first pushes touch in `--measure-stack`
+    // mode (where the canary spans the whole stack) -- reporting close to 0 bytes used every
+    // time instead of the true high-water mark.
+    let subroutine_address = start;
+
+    // 1. use probe-rs to search through the subroutine_len bytes where the subroutine is about
+    // to be placed. If the stack already grew this far, we don't even need to run anything
+    // on-target.
+    let mut canary = vec![0; subroutine_len];
+    core.read_8(subroutine_address, &mut canary)?;
+
+    // 2a. If we found a touched word, return the address of that
+    if let Some(touched) = first_touched_word(subroutine_address, &canary, seed) {
+        return Ok(Some(touched));
+    }
+    // 2b. If we did not, continue
+
+    // 3. Place subroutine in the area we've searched through, and have it scan upward from there
+    let subroutine = painter.measure(
+        subroutine_address + subroutine_len as u32,
+        size - subroutine_len as u32,
+        seed,
+    )?;
+    core.write_8(subroutine_address, &subroutine)?;
+
+    // 4. Execute subroutine
+    let previous_pc = core.read_core_reg(PC)?;
+    core.write_core_reg(PC, subroutine_address)?;
+    core.run()?;
+    core.wait_for_core_halted(TIMEOUT)?;
+    core.write_core_reg(PC, previous_pc)?;
+
+    const INITIAL_VALUE: u32 = 0;
+    let touched_value_address = core.read_core_reg(painter.result_register())?;
+    match touched_value_address == INITIAL_VALUE {
+        // 5a. If any address got touched, return Some(address)
+        false => Ok(Some(touched_value_address)),
+
+        // 5b. If no address go touched, return None
+        true => Ok(None),
+    }
+}
+
+/// Find the lowest word in `bytes` (read from `base`) that doesn't match its expected
+/// `address ^ seed` value.
+fn first_touched_word(base: u32, bytes: &[u8], seed: u32) -> Option<u32> {
+    bytes.chunks_exact(4).enumerate().find_map(|(i, word)| {
+        let addr = base + (i as u32) * 4;
+        let word = u32::from_le_bytes(word.try_into().unwrap());
+        (word != addr ^ seed).then_some(addr)
+    })
+}
@@ -0,0 +1,369 @@
+//! RISC-V (RV32I) paint/measure subroutines.
+
+use super::{check_aligned, CanaryError, StackPainter};
+
+pub(super) struct Riscv32StackPainter;
+
+impl StackPainter for Riscv32StackPainter {
+    fn paint_subroutine_len(&self) -> usize {
+        PAINT_SUBROUTINE_LENGTH
+    }
+
+    fn measure_subroutine_len(&self) -> usize {
+        MEASURE_SUBROUTINE_LENGTH
+    }
+
+    fn result_register(&self) -> probe_rs::CoreRegisterAddress {
+        // a0 (x10)
+        probe_rs::CoreRegisterAddress(A0)
+    }
+
+    fn paint(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+        paint_subroutine(start, size, seed)
+    }
+
+    fn measure(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+        measure_subroutine(start, size, seed)
+    }
+}
+
+// Register numbers (RISC-V calling convention names in comments).
+const ZERO: u16 = 0;
+const A0: u16 = 10;
+const A1: u16 = 11;
+const A2: u16 = 12;
+const A3: u16 = 13;
+const A4: u16 = 14;
+
+fn r_type(funct7: u32, rs2: u16, rs1: u16, funct3: u32, rd: u16, opcode: u32) -> u32 {
+    (funct7 << 25)
+        | ((rs2 as u32) << 20)
+        | ((rs1 as u32) << 15)
+        | (funct3 << 12)
+        | ((rd as u32) << 7)
+        | opcode
+}
+
+fn i_type(imm: i32, rs1: u16, funct3: u32, rd: u16, opcode: u32) -> u32 {
+    (((imm as u32) & 0xFFF) << 20)
+        | ((rs1 as u32) << 15)
+        | (funct3 << 12)
+        | ((rd as u32) << 7)
+        | opcode
+}
+
+fn s_type(imm: i32, rs2: u16, rs1: u16, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32 & 0xFFF;
+    let imm11_5 = (imm >> 5) & 0x7F;
+    let imm4_0 = imm & 0x1F;
+    (imm11_5 << 25)
+        | ((rs2 as u32) << 20)
+        | ((rs1 as u32) << 15)
+        | (funct3 << 12)
+        | (imm4_0 << 7)
+        | opcode
+}
+
+/// `imm` is a byte offset, must be even.
+fn b_type(imm: i32, rs2: u16, rs1: u16, funct3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    let imm12 = (imm >> 12) & 0x1;
+    let imm11 = (imm >> 11) & 0x1;
+    let imm10_5 = (imm >> 5) & 0x3F;
+    let imm4_1 = (imm >> 1) & 0xF;
+    (imm12 << 31)
+        | (imm10_5 << 25)
+        | ((rs2 as u32) << 20)
+        | ((rs1 as u32) << 15)
+        | (funct3 << 12)
+        | (imm4_1 << 8)
+        | (imm11 << 7)
+        | opcode
+}
+
+fn u_type(imm20: u32, rd: u16, opcode: u32) -> u32 {
+    (imm20 << 12) | ((rd as u32) << 7) | opcode
+}
+
+/// `imm` is a byte offset, must be even.
+fn j_type(imm: i32, rd: u16, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    let imm20 = (imm >> 20) & 0x1;
+    let imm10_1 = (imm >> 1) & 0x3FF;
+    let imm11 = (imm >> 11) & 0x1;
+    let imm19_12 = (imm >> 12) & 0xFF;
+    (imm20 << 31) | (imm10_1 << 21) | (imm11 << 20) | (imm19_12 << 12) | ((rd as u32) << 7) | opcode
+}
+
+fn beq(rs1: u16, rs2: u16, offset: i32) -> u32 {
+    b_type(offset, rs2, rs1, 0b000, 0b1100011)
+}
+
+fn bne(rs1: u16, rs2: u16, offset: i32) -> u32 {
+    b_type(offset, rs2, rs1, 0b001, 0b1100011)
+}
+
+fn xor(rd: u16, rs1: u16, rs2: u16) -> u32 {
+    r_type(0b0000000, rs2, rs1, 0b100, rd, 0b0110011)
+}
+
+fn sw(rs2: u16, offset: i32, rs1: u16) -> u32 {
+    s_type(offset, rs2, rs1, 0b010, 0b0100011)
+}
+
+fn lw(rd: u16, offset: i32, rs1: u16) -> u32 {
+    i_type(offset, rs1, 0b010, rd, 0b0000011)
+}
+
+fn addi(rd: u16, rs1: u16, imm: i32) -> u32 {
+    i_type(imm, rs1, 0b000, rd, 0b0010011)
+}
+
+fn jal(rd: u16, offset: i32) -> u32 {
+    j_type(offset, rd, 0b1101111)
+}
+
+const EBREAK: u32 = 0x0010_0073;
+
+/// Load an arbitrary 32-bit constant into `rd` using `lui`+`addi` (`li` pseudo-instruction).
+fn li(rd: u16, value: u32) -> [u32; 2] {
+    // Round to the nearest multiple of 0x1000 so that the sign-extended low 12 bits, added back
+    // via `addi`, reconstruct `value` exactly.
+    let hi = value.wrapping_add(0x800) >> 12;
+    let lo = value.wrapping_sub(hi << 12) as i32;
+    [u_type(hi & 0xF_FFFF, rd, 0b0110111), addi(rd, rd, lo)]
+}
+
+/// The length of the `paint_subroutine`.
+const PAINT_SUBROUTINE_LENGTH: usize = 48;
+
+/// Create a subroutine that paints every word from `start` till `start + size` with
+/// `address ^ seed`, i.e. a pattern that depends on the word's own address.
+///
+/// Both `start` and `size` need to be 4-byte-aligned.
+//
+// Roughly corresponds to the following assembly:
+//
+//  0:  li   a0, start
+//  8:  li   a1, end
+// 10:  li   a2, seed
+//
+// loop:
+// 18:  beq  a0, a1, end
+// 1c:  xor  a3, a0, a2
+// 20:  sw   a3, 0(a0)
+// 24:  addi a0, a0, 4
+// 28:  jal  zero, loop
+//
+// end:
+// 2c:  ebreak
+fn paint_subroutine(start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+    check_aligned(start)?;
+    check_aligned(size)?;
+
+    let end = start + size;
+
+    let mut words = Vec::with_capacity(PAINT_SUBROUTINE_LENGTH / 4);
+    words.extend(li(A0, start));
+    words.extend(li(A1, end));
+    words.extend(li(A2, seed));
+
+    let loop_word = words.len();
+    words.push(0); // beq a0, a1, end (patched below)
+    words.push(xor(A3, A0, A2));
+    words.push(sw(A3, 0, A0));
+    words.push(addi(A0, A0, 4));
+    words.push(0); // jal zero, loop (patched below)
+
+    let end_word = words.len();
+    words.push(EBREAK);
+
+    words[loop_word] = beq(A0, A1, ((end_word - loop_word) * 4) as i32);
+    let jal_word = end_word - 1;
+    words[jal_word] = jal(ZERO, -(((jal_word - loop_word) * 4) as i32));
+
+    debug_assert_eq!(words.len() * 4, PAINT_SUBROUTINE_LENGTH);
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+/// The length of the `measure_subroutine`.
+const MEASURE_SUBROUTINE_LENGTH: usize = 56;
+
+/// Create a subroutine that scans from `start` till `start + size`, one word at a time, looking
+/// for the first word that is not its expected `address ^ seed` value.
+///
+/// Returns (in `a0`) the address of the first touched word, or `0` if the whole range is still
+/// untouched.
+///
+/// Both `start` and `size` need to be 4-byte-aligned. `start` must not overlap the subroutine's
+/// own code, i.e. it must be >= the end address of wherever the subroutine is loaded.
+//
+// Roughly corresponds to the following assembly:
+//
+//  0:  li   a0, start
+//  8:  li   a1, end
+// 10:  li   a2, seed
+//
+// loop:
+// 18:  beq  a0, a1, done
+// 1c:  lw   a3, 0(a0)
+// 20:  xor  a4, a0, a2
+// 24:  bne  a3, a4, mismatch
+// 28:  addi a0, a0, 4
+// 2c:  jal  zero, loop
+//
+// done:
+// 30:  addi a0, zero, 0
+//
+// mismatch:
+// 34:  ebreak
+fn measure_subroutine(start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+    check_aligned(start)?;
+    check_aligned(size)?;
+
+    let end = start + size;
+
+    let mut words = Vec::with_capacity(MEASURE_SUBROUTINE_LENGTH / 4);
+    words.extend(li(A0, start));
+    words.extend(li(A1, end));
+    words.extend(li(A2, seed));
+
+    let loop_word = words.len();
+    words.push(0); // beq a0, a1, done (patched below)
+    words.push(lw(A3, 0, A0));
+    words.push(xor(A4, A0, A2));
+    words.push(0); // bne a3, a4, mismatch (patched below)
+    words.push(addi(A0, A0, 4));
+    words.push(0); // jal zero, loop (patched below)
+
+    let done_word = words.len();
+    words.push(addi(A0, ZERO, 0));
+
+    let mismatch_word = words.len();
+    words.push(EBREAK);
+
+    words[loop_word] = beq(A0, A1, ((done_word - loop_word) * 4) as i32);
+    let bne_word = loop_word + 3;
+    words[bne_word] = bne(A3, A4, ((mismatch_word - bne_word) * 4) as i32);
+    let jal_word = done_word - 1;
+    words[jal_word] = jal(ZERO, -(((jal_word - loop_word) * 4) as i32));
+
+    debug_assert_eq!(words.len() * 4, MEASURE_SUBROUTINE_LENGTH);
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words_of(bytes: &[u8]) -> Vec<u32> {
+        bytes
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect()
+    }
+
+    /// Reconstruct the byte offset encoded by a B-type branch instruction (`beq`/`bne`).
+    fn b_type_offset(instruction: u32) -> i32 {
+        let imm12 = (instruction >> 31) & 0x1;
+        let imm11 = (instruction >> 7) & 0x1;
+        let imm10_5 = (instruction >> 25) & 0x3F;
+        let imm4_1 = (instruction >> 8) & 0xF;
+        let imm = (imm12 << 12) | (imm11 << 11) | (imm10_5 << 5) | (imm4_1 << 1);
+        // Sign-extend from bit 12.
+        ((imm << 19) as i32) >> 19
+    }
+
+    /// Reconstruct the byte offset encoded by a J-type instruction (`jal`).
+    fn j_type_offset(instruction: u32) -> i32 {
+        let imm20 = (instruction >> 31) & 0x1;
+        let imm19_12 = (instruction >> 12) & 0xFF;
+        let imm11 = (instruction >> 20) & 0x1;
+        let imm10_1 = (instruction >> 21) & 0x3FF;
+        let imm = (imm20 << 20) | (imm19_12 << 12) | (imm11 << 11) | (imm10_1 << 1);
+        ((imm << 11) as i32) >> 11
+    }
+
+    #[test]
+    fn li_reconstructs_arbitrary_32_bit_values() {
+        for value in [
+            0,
+            1,
+            0xDEAD_BEEF,
+            0xFFFF_FFFF,
+            0x7FF,  // largest value representable by addi's imm alone
+            0x800,  // smallest value that requires lui to round up
+            0xFFFF_F800,
+            0x8000_0000,
+        ] {
+            let [lui, addi] = li(A0, value);
+            // lui loads imm20 << 12 into rd; addi then adds its sign-extended 12-bit immediate.
+            let lui_imm = ((lui >> 12) & 0xF_FFFF) as i32;
+            let addi_imm = (addi as i32) >> 20; // sign-extend the top 12 bits (addi's imm field)
+            let reconstructed = (lui_imm << 12).wrapping_add(addi_imm);
+            assert_eq!(
+                reconstructed as u32, value,
+                "li({:#010X}) didn't round-trip",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn paint_subroutine_patches_branches_to_the_right_targets() {
+        let bytes = paint_subroutine(0x2000_0100, 0x100, 0xDEAD_BEEF).unwrap();
+        assert_eq!(bytes.len(), PAINT_SUBROUTINE_LENGTH);
+        let words = words_of(&bytes);
+
+        // word indices: 0,1 = li a0; 2,3 = li a1; 4,5 = li a2; 6 = beq; 7 = xor; 8 = sw;
+        // 9 = addi; 10 = jal; 11 = ebreak.
+        let loop_word = 6;
+        let end_word = 11;
+        let jal_word = 10;
+
+        assert_eq!(b_type_offset(words[loop_word]), ((end_word - loop_word) * 4) as i32);
+        assert_eq!(
+            j_type_offset(words[jal_word]),
+            -(((jal_word - loop_word) * 4) as i32)
+        );
+        assert_eq!(words[end_word], EBREAK);
+    }
+
+    #[test]
+    fn measure_subroutine_patches_branches_to_the_right_targets() {
+        let bytes = measure_subroutine(0x2000_0100, 0x100, 0xDEAD_BEEF).unwrap();
+        assert_eq!(bytes.len(), MEASURE_SUBROUTINE_LENGTH);
+        let words = words_of(&bytes);
+
+        // word indices: 0,1 = li a0; 2,3 = li a1; 4,5 = li a2; 6 = beq; 7 = lw; 8 = xor;
+        // 9 = bne; 10 = addi; 11 = jal; 12 = done (addi a0, zero, 0); 13 = ebreak.
+        let loop_word = 6;
+        let bne_word = 9;
+        let done_word = 12;
+        let mismatch_word = 13;
+        let jal_word = 11;
+
+        assert_eq!(b_type_offset(words[loop_word]), ((done_word - loop_word) * 4) as i32);
+        assert_eq!(
+            b_type_offset(words[bne_word]),
+            ((mismatch_word - bne_word) * 4) as i32
+        );
+        assert_eq!(
+            j_type_offset(words[jal_word]),
+            -(((jal_word - loop_word) * 4) as i32)
+        );
+        assert_eq!(words[mismatch_word], EBREAK);
+    }
+
+    #[test]
+    fn rejects_misaligned_start_or_size() {
+        assert!(matches!(
+            paint_subroutine(0x2000_0101, 0x100, 0),
+            Err(CanaryError::MemoryAlignment { address: 0x2000_0101 })
+        ));
+        assert!(matches!(
+            measure_subroutine(0x2000_0100, 0x102, 0),
+            Err(CanaryError::MemoryAlignment { address: 0x102 })
+        ));
+    }
+}
@@ -0,0 +1,247 @@
+//! Cortex-M (Thumb) paint/measure subroutines.
+
+use super::{check_aligned, CanaryError, StackPainter};
+
+pub(super) struct ThumbStackPainter;
+
+impl StackPainter for ThumbStackPainter {
+    fn paint_subroutine_len(&self) -> usize {
+        PAINT_SUBROUTINE_LENGTH
+    }
+
+    fn measure_subroutine_len(&self) -> usize {
+        MEASURE_SUBROUTINE_LENGTH
+    }
+
+    fn result_register(&self) -> probe_rs::CoreRegisterAddress {
+        // r0
+        probe_rs::CoreRegisterAddress(0)
+    }
+
+    fn paint(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+        Ok(paint_subroutine(start, size, seed)?.to_vec())
+    }
+
+    fn measure(&self, start: u32, size: u32, seed: u32) -> Result<Vec<u8>, CanaryError> {
+        Ok(measure_subroutine(start, size, seed)?.to_vec())
+    }
+}
+
+/// The length of the `paint_subroutine`.
+const PAINT_SUBROUTINE_LENGTH: usize = 32;
+
+/// Create a subroutine that paints every word from `start` till `start + size` with
+/// `address ^ seed`, i.e. a pattern that depends on the word's own address.
+///
+/// Both `start` and `size` need to be 4-byte-aligned.
+//
+// Roughly corresponds to following assembly:
+//
+// 00000108 <start>:
+//  108:   4804        ldr r0, [pc, #16]   ; r0 = start (also the running pointer)
+//  10a:   4905        ldr r1, [pc, #20]   ; r1 = end
+//  10c:   4b05        ldr r3, [pc, #20]   ; r3 = seed
+//
+// 0000010e <loop>:
+//  10e:   4288        cmp r0, r1
+//  110:   d003        beq.n   11a <end>
+//  112:   0002        movs r2, r0
+//  114:   405a        eors r2, r3
+//  116:   c004        stmia   r0!, {r2}
+//  118:   e7f9        b.n 10e <loop>
+//
+// 0000011a <end>:
+//  11a:   be00        bkpt    0x0000
+//  11c:   20000100    .word   0x20000100  ; start
+//  120:   20000200    .word   0x20000200  ; end
+//  124:   deadbeef    .word   0xdeadbeef  ; seed
+fn paint_subroutine(
+    start: u32,
+    size: u32,
+    seed: u32,
+) -> Result<[u8; PAINT_SUBROUTINE_LENGTH], CanaryError> {
+    check_aligned(start)?;
+    check_aligned(size)?;
+
+    let end = start + size;
+
+    let [s1, s2, s3, s4] = start.to_le_bytes();
+    let [e1, e2, e3, e4] = end.to_le_bytes();
+    let [k1, k2, k3, k4] = seed.to_le_bytes();
+
+    Ok([
+        0x04, 0x48, // ldr r0, [pc, #16]
+        0x05, 0x49, // ldr r1, [pc, #20]
+        0x05, 0x4b, // ldr r3, [pc, #20]
+        // <loop>
+        0x88, 0x42, // cmp r0, r1
+        0x03, 0xD0, // beq.n   <end>
+        0x02, 0x00, // movs r2, r0
+        0x5A, 0x40, // eors r2, r3
+        0x04, 0xC0, // stmia   r0!, {r2}
+        0xF9, 0xE7, // b.n <loop>
+        // <end>
+        0x00, 0xBE, // bkpt    0x0000
+        //
+        s1, s2, s3, s4, // .word ; start address
+        e1, e2, e3, e4, // .word ; end address
+        k1, k2, k3, k4, // .word ; seed
+    ])
+}
+
+/// Create a subroutine that scans from `start` till `start + size`, one word at a time, looking
+/// for the first word that is not its expected `address ^ seed` value.
+///
+/// Returns (in `r0`) the address of the first touched word, or `0` if the whole range is still
+/// untouched.
+///
+/// Both `start` and `size` need to be 4-byte-aligned. `start` must not overlap the subroutine's
+/// own code, i.e. it must be >= the end address of wherever the subroutine is loaded.
+//
+// Roughly corresponds to the following assembly:
+//
+// 00000108 <start>:
+//  108:   4806        ldr r0, [pc, #24]   ; r0 = start (also the result register)
+//  10a:   4907        ldr r1, [pc, #28]   ; r1 = end
+//  10c:   4c07        ldr r4, [pc, #28]   ; r4 = seed
+//
+// 0000010e <loop>:
+//  10e:   4288        cmp r0, r1
+//  110:   d006        beq.n   120 <done>
+//  112:   6803        ldr r3, [r0]
+//  114:   0002        movs r2, r0
+//  116:   4062        eors r2, r4
+//  118:   4293        cmp r3, r2
+//  11a:   d102        bne.n   122 <mismatch>
+//  11c:   3004        adds    r0, #4
+//  11e:   e7f6        b.n 10e <loop>
+//
+// 00000120 <done>:
+//  120:   2000        movs    r0, #0
+//
+// 00000122 <mismatch>:
+//  122:   be00        bkpt    0x0000
+//  124:   20000100    .word   0x20000100  ; start
+//  128:   20000200    .word   0x20000200  ; end
+//  12c:   deadbeef    .word   0xdeadbeef  ; seed
+fn measure_subroutine(
+    start: u32,
+    size: u32,
+    seed: u32,
+) -> Result<[u8; MEASURE_SUBROUTINE_LENGTH], CanaryError> {
+    check_aligned(start)?;
+    check_aligned(size)?;
+
+    let end = start + size;
+
+    let [s1, s2, s3, s4] = start.to_le_bytes();
+    let [e1, e2, e3, e4] = end.to_le_bytes();
+    let [k1, k2, k3, k4] = seed.to_le_bytes();
+
+    Ok([
+        0x06, 0x48, // ldr r0, [pc, #24]
+        0x07, 0x49, // ldr r1, [pc, #28]
+        0x07, 0x4c, // ldr r4, [pc, #28]
+        // <loop>
+        0x88, 0x42, // cmp r0, r1
+        0x06, 0xD0, // beq.n   <done>
+        0x03, 0x68, // ldr r3, [r0]
+        0x02, 0x00, // movs r2, r0
+        0x62, 0x40, // eors r2, r4
+        0x93, 0x42, // cmp r3, r2
+        0x02, 0xD1, // bne.n   <mismatch>
+        0x04, 0x30, // adds r0, #4
+        0xF6, 0xE7, // b.n <loop>
+        // <done>
+        0x00, 0x20, // movs r0, #0
+        // <mismatch>
+        0x00, 0xBE, // bkpt    0x0000
+        //
+        s1, s2, s3, s4, // .word ; start address
+        e1, e2, e3, e4, // .word ; end address
+        k1, k2, k3, k4, // .word ; seed
+    ])
+}
+
+/// The length of the `measure_subroutine`.
+const MEASURE_SUBROUTINE_LENGTH: usize = 40;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_subroutine_encodes_literals_and_rejects_misalignment() {
+        let start = 0x2000_0100;
+        let size = 0x100;
+        let seed = 0xDEAD_BEEF;
+
+        let bytes = paint_subroutine(start, size, seed).unwrap();
+        assert_eq!(bytes.len(), PAINT_SUBROUTINE_LENGTH);
+
+        // The fixed instruction prologue/loop/epilogue never changes; only the trailing literal
+        // pool depends on the arguments.
+        assert_eq!(
+            &bytes[..20],
+            &[
+                0x04, 0x48, // ldr r0, [pc, #16]
+                0x05, 0x49, // ldr r1, [pc, #20]
+                0x05, 0x4b, // ldr r3, [pc, #20]
+                0x88, 0x42, // cmp r0, r1
+                0x03, 0xD0, // beq.n <end>
+                0x02, 0x00, // movs r2, r0
+                0x5A, 0x40, // eors r2, r3
+                0x04, 0xC0, // stmia r0!, {r2}
+                0xF9, 0xE7, // b.n <loop>
+                0x00, 0xBE, // bkpt 0x0000
+            ]
+        );
+        assert_eq!(&bytes[20..24], &start.to_le_bytes());
+        assert_eq!(&bytes[24..28], &(start + size).to_le_bytes());
+        assert_eq!(&bytes[28..32], &seed.to_le_bytes());
+
+        assert!(matches!(
+            paint_subroutine(start + 1, size, seed),
+            Err(CanaryError::MemoryAlignment { address }) if address == start + 1
+        ));
+        assert!(matches!(
+            paint_subroutine(start, size + 2, seed),
+            Err(CanaryError::MemoryAlignment { address }) if address == size + 2
+        ));
+    }
+
+    #[test]
+    fn measure_subroutine_encodes_literals_and_rejects_misalignment() {
+        let start = 0x2000_0100;
+        let size = 0x100;
+        let seed = 0xDEAD_BEEF;
+
+        let bytes = measure_subroutine(start, size, seed).unwrap();
+        assert_eq!(bytes.len(), MEASURE_SUBROUTINE_LENGTH);
+
+        assert_eq!(
+            &bytes[..28],
+            &[
+                0x06, 0x48, // ldr r0, [pc, #24]
+                0x07, 0x49, // ldr r1, [pc, #28]
+                0x07, 0x4c, // ldr r4, [pc, #28]
+                0x88, 0x42, // cmp r0, r1
+                0x06, 0xD0, // beq.n <done>
+                0x03, 0x68, // ldr r3, [r0]
+                0x02, 0x00, // movs r2, r0
+                0x62, 0x40, // eors r2, r4
+                0x93, 0x42, // cmp r3, r2
+                0x02, 0xD1, // bne.n <mismatch>
+                0x04, 0x30, // adds r0, #4
+                0xF6, 0xE7, // b.n <loop>
+                0x00, 0x20, // movs r0, #0
+                0x00, 0xBE, // bkpt 0x0000
+            ]
+        );
+        assert_eq!(&bytes[28..32], &start.to_le_bytes());
+        assert_eq!(&bytes[32..36], &(start + size).to_le_bytes());
+        assert_eq!(&bytes[36..40], &seed.to_le_bytes());
+
+        assert!(measure_subroutine(start, size + 1, seed).is_err());
+    }
+}
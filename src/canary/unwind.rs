@@ -0,0 +1,125 @@
+//! Attribute the canary's measured high-water mark to the stack frame that caused it.
+//!
+//! Walks the call stack at the halt point using the target's DWARF call-frame information
+//! (`.debug_frame`), so `Canary::touched` can report *which* function's frame reached the
+//! touched address instead of only how many bytes were used.
+
+use gimli::{
+    BaseAddresses, CfaRule, LittleEndian, Register, RegisterRule, UninitializedUnwindContext,
+    UnwindSection,
+};
+use probe_rs::{Architecture, Core, CoreRegisterAddress};
+
+use crate::{registers::PC, Elf};
+
+use super::CanaryError;
+
+/// The DWARF register numbers (which probe-rs also uses as `CoreRegisterAddress`es) of `sp` and
+/// the return-address register, for the given core architecture.
+///
+/// `sp`'s value is the innermost frame's own CFA-relative starting point; the return-address
+/// register is the one the CFI return-address rule is evaluated for.
+fn sp_and_lr(architecture: Architecture) -> (u16, u16) {
+    match architecture {
+        Architecture::Riscv => (2, 1),  // sp (x2), ra (x1)
+        _ => (13, 14),                  // sp (r13), lr (r14)
+    }
+}
+
+/// One frame on the call stack at the moment the core halted, innermost first.
+pub(super) struct Frame {
+    /// The function's symbol name, demangled if possible, or `None` if it couldn't be resolved.
+    pub(super) function: Option<String>,
+    /// `file:line` for the frame's program counter, or `None` if no debug info covers it.
+    pub(super) location: Option<String>,
+    /// Bytes of stack this frame itself occupies, i.e. its own CFA minus the callee's CFA.
+    pub(super) size: u32,
+    /// Whether this frame is confirmed to be the one whose allocation covers
+    /// `touched_address`, rather than just the last frame we could unwind to before CFI ran out.
+    pub(super) is_culprit: bool,
+}
+
+/// Unwind the call stack at the halted core, from the current program counter outward, and
+/// return every frame up to and including the one whose allocation covers `touched_address`.
+///
+/// Returns `None` if the target has no `.debug_frame` section to unwind with. The returned
+/// `Vec` may end before reaching `touched_address` if CFI runs out (e.g. hand-written
+/// assembly without call-frame info); in that case the last frame is the deepest one we could
+/// still attribute.
+pub(super) fn frames_above(
+    elf: &Elf,
+    core: &mut Core,
+    touched_address: u32,
+) -> Result<Option<Vec<Frame>>, CanaryError> {
+    let debug_frame = match elf.debug_frame_section() {
+        Some(section) => gimli::DebugFrame::new(section, LittleEndian),
+        None => return Ok(None),
+    };
+    let (sp, lr) = sp_and_lr(core.architecture());
+    let bases = BaseAddresses::default();
+    let mut unwind_ctx = UninitializedUnwindContext::new();
+
+    let mut pc = u64::from(core.read_core_reg(PC)?);
+    let mut cfa = u64::from(core.read_core_reg(CoreRegisterAddress(sp))?);
+    let mut frames = Vec::new();
+
+    loop {
+        let unwind_info = match debug_frame.unwind_info_for_address(
+            &bases,
+            &mut unwind_ctx,
+            pc,
+            gimli::DebugFrame::cie_from_offset,
+        ) {
+            Ok(unwind_info) => unwind_info,
+            // No CFI covers this address; stop rather than guess at the frame layout.
+            Err(_) => break,
+        };
+
+        let next_cfa = match unwind_info.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let value = if register.0 == sp {
+                    cfa
+                } else {
+                    u64::from(core.read_core_reg(CoreRegisterAddress(register.0.into()))?)
+                };
+                value.wrapping_add(*offset as u64)
+            }
+            // We don't evaluate arbitrary DWARF expressions for the CFA.
+            CfaRule::Expression(_) => break,
+        };
+
+        let is_culprit = cfa <= u64::from(touched_address) && u64::from(touched_address) < next_cfa;
+
+        frames.push(Frame {
+            function: elf.function_at(pc as u32),
+            location: elf.location_at(pc as u32),
+            size: (next_cfa - cfa) as u32,
+            is_culprit,
+        });
+
+        if is_culprit {
+            break;
+        }
+
+        let return_address = match unwind_info.register(Register(lr)) {
+            RegisterRule::Offset(offset) => {
+                let address = (next_cfa as i64).wrapping_add(offset) as u32;
+                let mut bytes = [0; 4];
+                core.read_8(address, &mut bytes)?;
+                u32::from_le_bytes(bytes)
+            }
+            // No rule to recover the caller's return address; this is the outermost frame we
+            // can unwind to.
+            _ => break,
+        };
+
+        if return_address == 0 {
+            break;
+        }
+
+        pc = u64::from(return_address);
+        cfa = next_cfa;
+    }
+
+    Ok(Some(frames))
+}
@@ -0,0 +1,34 @@
+//! `Elf` accessors used by the stack-canary call-frame unwinder ([`crate::canary::unwind`]).
+//!
+//! `Elf` itself (its fields, ELF/object parsing and the rest of its accessors, like
+//! `vector_table` and `program_uses_heap`) lives elsewhere in the crate; this file only adds
+//! the few DWARF lookups the canary's frame attribution needs, kept separate since this series
+//! otherwise only touches `src/canary/*`.
+
+use object::Object as _;
+
+use crate::Elf;
+
+impl Elf {
+    /// Raw bytes of the `.debug_frame` section, if the binary has one.
+    pub(crate) fn debug_frame_section(&self) -> Option<&[u8]> {
+        let section = self.object_file().section_by_name(".debug_frame")?;
+        section.data().ok()
+    }
+
+    /// The name of the function whose range covers `pc`, demangled if possible.
+    pub(crate) fn function_at(&self, pc: u32) -> Option<String> {
+        let mut frames = self.addr2line_context().find_frames(u64::from(pc)).ok()?;
+        let frame = frames.next().ok()??;
+        let name = frame.function?.demangle().ok()?.into_owned();
+        Some(name)
+    }
+
+    /// `file:line` for `pc`, if debug info covers it.
+    pub(crate) fn location_at(&self, pc: u32) -> Option<String> {
+        let location = self.addr2line_context().find_location(u64::from(pc)).ok()??;
+        let file = location.file?;
+        let line = location.line?;
+        Some(format!("{}:{}", file, line))
+    }
+}